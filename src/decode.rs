@@ -0,0 +1,85 @@
+//! Decodes a snapshot file into an 8-bit RGB image, routing formats the `image` crate can't read
+//! natively through optional, feature-gated decoders before falling back to `image` for
+//! everything else.
+
+use anyhow::{Context, Result};
+use image::io::Reader as ImageReader;
+use image::{ImageBuffer, Rgb};
+use std::path::Path;
+
+/// Decode the image at `path`, picking a decoder based on its extension.
+pub fn decode_snapshot(path: &Path) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        #[cfg(feature = "heif")]
+        "heic" | "heif" => decode_heif(path),
+
+        #[cfg(feature = "raw")]
+        "cr2" | "cr3" | "nef" | "arw" | "dng" | "raf" | "orf" | "rw2" | "pef" | "srw" => {
+            decode_raw(path)
+        }
+
+        _ => decode_native(path),
+    }
+}
+
+/// Decode anything the `image` crate supports natively.
+fn decode_native(path: &Path) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    Ok(ImageReader::open(path)
+        .with_context(|| format!("failed to read {path:?}"))?
+        .decode()
+        .with_context(|| format!("failed to decode {path:?}"))?
+        .into_rgb8())
+}
+
+/// Decode a HEIC/HEIF file via `libheif-rs`. Requires the `heif` cargo feature.
+#[cfg(feature = "heif")]
+fn decode_heif(path: &Path) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib_heif = LibHeif::new();
+
+    let ctx = HeifContext::read_from_file(&path.to_string_lossy())
+        .with_context(|| format!("failed to read {path:?}"))?;
+
+    let handle = ctx
+        .primary_image_handle()
+        .with_context(|| format!("no primary image in {path:?}"))?;
+
+    let image = lib_heif
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .with_context(|| format!("failed to decode {path:?}"))?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .with_context(|| format!("decoded HEIF image {path:?} has no interleaved RGB plane"))?;
+
+    let width = plane.width;
+    let height = plane.height;
+
+    // The plane may be padded to `plane.stride` bytes per row, so copy row-by-row rather than
+    // taking the buffer as-is.
+    let mut buf = Vec::with_capacity((width * height * 3) as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        buf.extend_from_slice(&row[..(width * 3) as usize]);
+    }
+
+    ImageBuffer::from_raw(width, height, buf)
+        .with_context(|| format!("decoded HEIF buffer for {path:?} had the wrong size"))
+}
+
+/// Decode a camera RAW file via `imagepipe`. Requires the `raw` cargo feature.
+#[cfg(feature = "raw")]
+fn decode_raw(path: &Path) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    let decoded = imagepipe::simple_decode_8bit(path, 0, 0)
+        .map_err(|e| anyhow::anyhow!("{e}"))
+        .with_context(|| format!("failed to process RAW image {path:?}"))?;
+
+    ImageBuffer::from_raw(decoded.width as u32, decoded.height as u32, decoded.data)
+        .with_context(|| format!("decoded RAW buffer for {path:?} had the wrong size"))
+}
@@ -1,26 +1,30 @@
 //! Reads the latest file in the given directory whose file name starts with "vlcsnap-",
 //! automatically crops borders of black pixels from the image, lightens the image by the specified
-//! amount, and sends it to a default printer.
-//! Currently, printing only works on Windows.
+//! amount, and sends it to a printer. A different file-name pattern can be given with `--pattern`.
 
 #![deny(missing_docs)]
 
+mod decode;
+mod printer;
+mod selection;
+
 use anyhow::{Context, Result};
 use argh::FromArgs;
-use image::io::Reader as ImageReader;
-use image::{buffer::Pixels, imageops, ImageBuffer, Pixel, SubImage};
-use std::fs::{self, DirEntry};
+use decode::decode_snapshot;
+use image::{imageops, ImageBuffer, Pixel, SubImage};
+use printer::Printer;
+use selection::SortBy;
+use std::collections::HashSet;
+use std::fs;
 use std::path::{PathBuf, Path};
-use std::time::SystemTime;
+use std::thread;
+use std::time::Duration;
 use pbr::ProgressBar;
-use std::process::Command;
-use anyhow::bail;
 
 #[derive(FromArgs)]
 /// Reads the latest file in the given directory whose file name starts with "vlcsnap-",
 /// automatically crops borders of black pixels from the image, lightens the image by the specified
-/// amount, and sends it to a default printer.
-/// Currently only works on Windows.
+/// amount, and sends it to a printer. A different file-name pattern can be given with `--pattern`.
 struct Args {
     /// which directory to look for snapshots in; you probably want this to be the same directory
     /// that VLC is set to save snapshots to.
@@ -30,97 +34,127 @@ struct Args {
     /// where to map zero in the squooshed 0..u16::MAX range
     #[argh(option, short = 'l')]
     luma: u8,
+
+    /// which printer to send the snapshot to; defaults to the system default printer
+    #[argh(option, short = 'p')]
+    printer: Option<String>,
+
+    /// how many copies to print
+    #[argh(option, short = 'n', default = "1")]
+    copies: u32,
+
+    /// instead of processing the most recent snapshot once, watch `snapshot_dir` and process
+    /// every new snapshot as VLC writes it
+    #[argh(switch, short = 'w')]
+    watch: bool,
+
+    /// how far above the detected black level (in luma, 0-255) a pixel must be to count as
+    /// content rather than border
+    #[argh(option, default = "12")]
+    crop_delta: u8,
+
+    /// the fraction of a row's or column's pixels that must exceed the crop threshold before
+    /// that row/column counts as content rather than border noise
+    #[argh(option, default = "0.02")]
+    crop_fraction: f32,
+
+    /// glob pattern (supporting `*` wildcards) used to select candidate snapshot files
+    #[argh(option, default = "String::from(\"vlcsnap-*\")")]
+    pattern: String,
+
+    /// which timestamp to sort candidate snapshot files by; `created` falls back to `modified`
+    /// on filesystems that don't report creation time
+    #[argh(option, default = "SortBy::Created")]
+    sort_by: SortBy,
 }
 
-/// Guess the `(left, right)` endpoints of the image content at this row, bounded by extra black
-/// pixels. Operates in one pass and consumes the row. If the entire row consists of black pixels,
-/// the left bound will be the largest possible index of the array.
-fn row_bounds<P>(mut row: Pixels<P>) -> (u32, u32)
+/// Estimate the image's black level by downsampling it to a small thumbnail and taking a low
+/// percentile of its luma histogram, so that a uniformly dark (but not pure black) border is
+/// still recognized as border.
+fn black_level<P>(img: &ImageBuffer<P, Vec<u8>>) -> u8
 where
-    P: Pixel<Subpixel = u8>,
+    P: Pixel<Subpixel = u8> + 'static,
 {
-    // First row segment: find the leftmost position at which a non-black pixel appears
-    let mut left_bound = 0;
-    while let Some(true) = row.next().map(|p| p.to_luma()[0] < 16) {
-        left_bound += 1;
-    }
+    const THUMBNAIL_EDGE: u32 = 32;
+    const PERCENTILE: f32 = 0.05;
 
-    // Second row segment: find the rightmost position at which a non-black pixel appears
-    let mut right_bound = 0;
-    let mut cursor = left_bound;
-    while row.len() != 0 {
-        // Consume a segment of non-black pixels
-        while let Some(false) = row.next().map(|p| p.to_luma()[0] < 16) {
-            cursor += 1;
-        }
+    let (width, height) = img.dimensions();
+    let scale = THUMBNAIL_EDGE as f32 / width.max(height) as f32;
+    let thumb_width = ((width as f32 * scale).round() as u32).max(1);
+    let thumb_height = ((height as f32 * scale).round() as u32).max(1);
+
+    let thumbnail = imageops::resize(img, thumb_width, thumb_height, imageops::FilterType::Triangle);
 
-        // The end of that segment is our current guess for the right bound
-        right_bound = cursor;
+    let mut histogram = [0u32; 256];
+    for pixel in thumbnail.pixels() {
+        histogram[pixel.to_luma()[0] as usize] += 1;
+    }
+
+    let total: u32 = histogram.iter().sum();
+    let target = (total as f32 * PERCENTILE).ceil() as u32;
 
-        // Consume the following segment of black pixels
-        while let Some(true) = row.next().map(|p| p.to_luma()[0] < 16) {
-            cursor += 1
+    let mut cumulative = 0;
+    for (level, &count) in histogram.iter().enumerate() {
+        cumulative += count;
+        if cumulative >= target {
+            return level as u8;
         }
     }
 
-    (left_bound, right_bound)
+    0
 }
 
-/// Crop out bordering black pixels
-fn auto_crop<P>(img: &mut ImageBuffer<P, Vec<u8>>) -> SubImage<&mut ImageBuffer<P, Vec<u8>>>
+/// Crop out the bordering black pixels. A row or column only counts as border if fewer than
+/// `fraction` of its pixels exceed `threshold`, so that thin noise (JPEG ringing, subtitle
+/// specks, a slightly-gray letterbox) doesn't defeat the crop. If no row and column both clear
+/// that bar anywhere in the image (a uniformly dim frame, a fade-to-black, a solid-color title
+/// card), the image is left uncropped rather than computing a negative-size crop.
+fn auto_crop<P>(
+    img: &mut ImageBuffer<P, Vec<u8>>,
+    threshold: u8,
+    fraction: f32,
+) -> SubImage<&mut ImageBuffer<P, Vec<u8>>>
 where
     P: Pixel<Subpixel = u8>,
 {
-    let img_width = img.width();
-    let mut rows = img.rows();
-
-    let mut right_crop = 0;
-    let mut top_crop = 0;
-    let mut left_crop = img_width;
-    let mut bot_crop = 0;
+    let (width, height) = img.dimensions();
 
-    // First col segment: find the topmost position at which a non-black row appears
-    while let Some(true) = rows.next().map(|r| row_bounds(r).0 == img_width) {
-        top_crop += 1;
-    }
+    // One pass over every pixel, tallying how many pixels in each row and in each column exceed
+    // `threshold`.
+    let mut column_counts = vec![0u32; width as usize];
+    let mut row_is_content = vec![false; height as usize];
 
-    // Second col segment: find the botmost position at which a non-black row appears
-    let mut cursor = top_crop;
-    while rows.len() != 0 {
-        // Consume a segment of non-black rows
-        while let Some(row) = rows.next() {
-            let (row_left_crop, row_right_crop) = row_bounds(row);
-            if row_right_crop > right_crop {
-                right_crop = row_right_crop;
-            }
+    for (y, row) in img.rows().enumerate() {
+        let mut row_count = 0u32;
 
-            if row_left_crop < left_crop {
-                left_crop = row_left_crop;
-            }
-
-            cursor += 1;
-
-            if row_left_crop == img_width {
-                break;
+        for (x, pixel) in row.enumerate() {
+            if pixel.to_luma()[0] >= threshold {
+                row_count += 1;
+                column_counts[x] += 1;
             }
         }
 
-        // The end of that segment is our current guess for the bot bound
-        bot_crop = cursor;
-
-        // Consume the following segment of black rows
-        while let Some(true) = rows.next().map(|r| row_bounds(r).0 == img_width) {
-            cursor += 1;
-        }
+        row_is_content[y] = row_count as f32 / width as f32 >= fraction;
     }
 
-    imageops::crop(
-        img,
-        left_crop,
-        top_crop,
-        right_crop - left_crop,
-        bot_crop - top_crop,
-    )
+    let is_content_column = |count: &u32| *count as f32 / height as f32 >= fraction;
+
+    let top_crop = row_is_content.iter().position(|c| *c);
+    let bot_crop = row_is_content.iter().rposition(|c| *c).map(|i| i + 1);
+    let left_crop = column_counts.iter().position(is_content_column);
+    let right_crop = column_counts.iter().rposition(is_content_column).map(|i| i + 1);
+
+    match (top_crop, bot_crop, left_crop, right_crop) {
+        (Some(top), Some(bot), Some(left), Some(right)) => imageops::crop(
+            img,
+            left as u32,
+            top as u32,
+            (right - left) as u32,
+            (bot - top) as u32,
+        ),
+        // No row and column both cleared the content bar anywhere: leave the image as-is.
+        _ => imageops::crop(img, 0, 0, width, height),
+    }
 }
 
 fn auto_brighten<P>(img: &mut ImageBuffer<P, Vec<u8>>, luma: u8)
@@ -134,62 +168,59 @@ where
     };
 
     img.pixels_mut()
-        .map(|p| p.channels_mut())
-        .flatten()
+        .flat_map(|p| p.channels_mut())
         .for_each(scale);
 }
 
-fn most_recent_file(dir: &Path) -> Result<PathBuf> {
-    #[derive(PartialEq, Eq, PartialOrd, Ord)]
-    struct FileEntryHelper {
-        created: SystemTime,
-        path: PathBuf,
-        is_file: bool,
-    }
+/// Compute the path that the cropped, brightened copy of `orig_path` should be written to.
+fn output_path_for(orig_path: &Path) -> Result<PathBuf> {
+    let orig_name = orig_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .context("invalid UTF-8 in file name")?
+        .to_owned();
 
-    impl FileEntryHelper {
-        // Unwrap all of the inner `Results` in a `DirEntry` and shove the wanted properties into a
-        // new `FileEntryHelper`.
-        fn from_dir_entry(dir_entry: DirEntry) -> Result<Self> {
-            let path = dir_entry.path();
+    let orig_extension = orig_path
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .context("invalid UTF-8 in file name")?;
 
-            let metadata = dir_entry.metadata().with_context(|| format!("couldn't get metadata of file {path:?}"))?;
+    Ok(orig_path
+        .with_file_name(orig_name + "-vlc-print-out")
+        .with_extension(orig_extension))
+}
 
-            let created = metadata.created().with_context(|| format!("couldn't get creation date of file {path:?}"))?;
+/// Run the crop→brighten→print pipeline on `path`, writing the result next to it and sending it
+/// to `printer`. Shared by `run_once` and `watch` so there's only one copy of the pipeline to keep
+/// in sync as it grows.
+fn process_snapshot(path: &Path, args: &Args, printer: &Printer) -> Result<PathBuf> {
+    let mut img = decode_snapshot(path)?;
 
-            Ok(Self { created, path, is_file: metadata.is_file() })
-        }
+    let threshold = black_level(&img).saturating_add(args.crop_delta);
+    let mut cropped_img = auto_crop(&mut img, threshold, args.crop_fraction).to_image();
+
+    if args.luma != 0 {
+        auto_brighten(&mut cropped_img, args.luma);
     }
 
-    let entries = fs::read_dir(dir).context("failed to read directory")?;
+    let new_path = output_path_for(path)?;
 
-    let mut files = Vec::new();
-    for entry in entries {
-        match entry.context("couldn't read file in given directory").and_then(FileEntryHelper::from_dir_entry) {
-            Ok(entry) => {
-                if entry.is_file && entry.path.file_stem().map_or(true, |s| !s.to_string_lossy().contains("vlc-print-out")) {
-                    files.push(entry);
-                }
-            }
-            Err(e) => {
-                let mut chain = e.chain();
-                eprintln!("warning: {}\n", chain.next().unwrap());
-                for err in chain {
-                    eprintln!("caused by: {err}\n");
-                }
-            }
-        }
-    }
+    cropped_img
+        .save(&new_path)
+        .with_context(|| format!("failed to save cropped image to {:?}", new_path))?;
 
-    let latest_file = files.into_iter().max_by_key(|f| f.created);
+    printer
+        .print(&new_path, args.printer.as_deref(), args.copies)
+        .context("failed to print image")?;
 
-    Ok(latest_file.context("no valid files in directory")?.path)
+    Ok(new_path)
 }
 
-fn go() -> Result<()> {
-    let args: Args = argh::from_env();
-
-    let mut pb = ProgressBar::new(6);
+/// Run the crop→brighten→print pipeline once on the most recent snapshot in `args.snapshot_dir`.
+fn run_once(args: &Args) -> Result<()> {
+    let mut pb = ProgressBar::new(2);
     pb.format("[=> ]");
     pb.show_percent = false;
     pb.show_speed = false;
@@ -197,65 +228,90 @@ fn go() -> Result<()> {
     pb.message("finding image ");
     pb.tick();
 
-    let orig_path = most_recent_file(&args.snapshot_dir).context("failed to get most recent file in given directory")?;
+    let orig_path = selection::most_recent_file(&args.snapshot_dir, &args.pattern, args.sort_by)
+        .context("failed to get most recent file in given directory")?;
 
-    pb.message("opening image ");
+    pb.message("processing image ");
     pb.inc();
 
-    let mut img = ImageReader::open(&orig_path)
-        .with_context(|| format!("failed to read {:?}", orig_path))?
-        .decode()
-        .with_context(|| format!("failed to decode {:?}", orig_path))?
-        .into_rgb8();
+    process_snapshot(&orig_path, args, &Printer::for_platform())?;
 
-    pb.message("cropping image ");
     pb.inc();
 
-    let mut cropped_img = auto_crop(&mut img).to_image();
+    Ok(())
+}
 
-    pb.message("brightening image ");
-    pb.inc();
+/// Check whether `path`'s size and modification time are unchanged across a `delay`-long pause,
+/// i.e. whether whatever is writing it has finished.
+fn is_stable(path: &Path, delay: Duration) -> Result<bool> {
+    let before = fs::metadata(path).with_context(|| format!("couldn't get metadata of file {path:?}"))?;
+    thread::sleep(delay);
+    let after = fs::metadata(path).with_context(|| format!("couldn't get metadata of file {path:?}"))?;
 
-    if args.luma != 0 {
-        auto_brighten(&mut cropped_img, args.luma);
-    }
+    Ok(before.len() == after.len() && before.modified()? == after.modified()?)
+}
 
-    pb.message("writing image ");
-    pb.inc();
+/// `true` if `path` is a file whose name matches `pattern` and isn't one of our own
+/// cropped-and-brightened output files.
+fn is_snapshot(path: &Path, pattern: &str) -> bool {
+    path.is_file()
+        && path.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            selection::matches_pattern(&name, pattern) && !name.contains("-vlc-print-out")
+        })
+}
 
-    let orig_name = orig_path
-        .file_stem()
-        .unwrap_or_default()
-        .to_str()
-        .context("invalid UTF-8 in file name")?
-        .to_owned();
+/// Watch `args.snapshot_dir` indefinitely, running the crop→brighten→print pipeline on every new
+/// snapshot as VLC writes it.
+fn watch(args: &Args) -> Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-    let orig_extension = orig_path
-        .extension()
-        .unwrap_or_default()
-        .to_str()
-        .context("invalid UTF-8 in file name")?;
+    println!("watching {:?} for new snapshots (press ctrl-c to stop)", args.snapshot_dir);
 
-    let new_path = orig_path
-        .with_file_name(orig_name + "-vlc-print-out")
-        .with_extension(orig_extension);
+    let printer = Printer::for_platform();
 
-    cropped_img
-        .save(&new_path)
-        .with_context(|| format!("failed to save cropped image to {:?}", new_path))?;
+    // Snapshots already sitting in the directory when we start aren't "new"; seed `processed`
+    // with them so only files that appear from here on get printed.
+    let mut processed: HashSet<PathBuf> = fs::read_dir(&args.snapshot_dir)
+        .context("failed to read directory")?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| is_snapshot(path, &args.pattern))
+        .collect();
 
-    pb.message("printing image ");
-    pb.inc();
+    loop {
+        let entries = fs::read_dir(&args.snapshot_dir).context("failed to read directory")?;
 
-    if cfg!(target_os = "windows") {
-        Command::new("mspaint").arg("/p").arg(new_path).output().context("couldn't print through mspaint")?;
-    } else {
-        bail!("it looks like you aren't running this on Windows");
+        for entry in entries {
+            let path = entry.context("couldn't read file in given directory")?.path();
+
+            if !is_snapshot(&path, &args.pattern) || processed.contains(&path) {
+                continue;
+            }
+
+            if !is_stable(&path, POLL_INTERVAL).with_context(|| format!("couldn't check stability of {path:?}"))? {
+                continue;
+            }
+
+            processed.insert(path.clone());
+
+            match process_snapshot(&path, args, &printer) {
+                Ok(_) => println!("printed {path:?}"),
+                Err(e) => eprintln!("warning: failed to process {path:?}: {e}"),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
     }
+}
 
-    pb.inc();
+fn go() -> Result<()> {
+    let args: Args = argh::from_env();
 
-    Ok(())
+    if args.watch {
+        watch(&args)
+    } else {
+        run_once(&args)
+    }
 }
 
 fn main() {
@@ -269,7 +325,7 @@ fn main() {
                 for cause in chain {
                     println!("\t{cause}");
                 }
-                println!("");
+                println!();
             }
 
             println!("press the 'x' button in the upper right corner of this window to close it");
@@ -0,0 +1,83 @@
+//! Abstracts over the platform-specific ways of sending a file to a printer, so that `go()`
+//! doesn't need to know whether it's running under Linux, macOS, or Windows.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A printer backend, chosen automatically for the host platform.
+pub enum Printer {
+    /// Print via CUPS's `lp` command, as found on Linux and macOS.
+    Cups,
+    /// Print via a Windows print-spooler verb.
+    Windows,
+}
+
+impl Printer {
+    /// Pick the printer backend appropriate for the host platform.
+    pub fn for_platform() -> Self {
+        if cfg!(target_os = "windows") {
+            Printer::Windows
+        } else {
+            Printer::Cups
+        }
+    }
+
+    /// Send the file at `path` to `printer_name` (or the system default printer, if `None`),
+    /// printing `copies` copies of it.
+    pub fn print(&self, path: &Path, printer_name: Option<&str>, copies: u32) -> Result<()> {
+        match self {
+            Printer::Cups => self.print_cups(path, printer_name, copies),
+            Printer::Windows => self.print_windows(path, printer_name, copies),
+        }
+    }
+
+    fn print_cups(&self, path: &Path, printer_name: Option<&str>, copies: u32) -> Result<()> {
+        let mut cmd = Command::new("lp");
+
+        if let Some(name) = printer_name {
+            cmd.arg("-d").arg(name);
+        }
+
+        cmd.arg("-n").arg(copies.to_string()).arg(path);
+
+        let status = cmd.status().context("couldn't invoke `lp`")?;
+
+        if !status.success() {
+            bail!("`lp` exited with {status}");
+        }
+
+        Ok(())
+    }
+
+    fn print_windows(&self, path: &Path, printer_name: Option<&str>, copies: u32) -> Result<()> {
+        // `shimgvw.dll`'s `ImageView_Print`/`ImageView_PrintTo` verbs go straight to the print
+        // spooler, unlike `mspaint /p`, which briefly pops a visible Paint window first.
+        for _ in 0..copies {
+            let mut cmd = Command::new("rundll32");
+
+            match printer_name {
+                Some(name) => {
+                    cmd.arg("shimgvw.dll,ImageView_PrintTo").arg(path).arg(name);
+                }
+                None => {
+                    cmd.arg("shimgvw.dll,ImageView_Print").arg(path);
+                }
+            }
+
+            let output = cmd
+                .output()
+                .context("couldn't print through the Windows print spooler")?;
+
+            if !output.status.success() {
+                bail!(
+                    "`rundll32 shimgvw.dll` exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Chooses which file in a directory to treat as "the" snapshot to process, generalizing beyond
+//! a hard-coded `"vlcsnap-"` prefix and `created()` timestamps (which many Linux filesystems
+//! don't support).
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, DirEntry};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Which file timestamp to sort candidate snapshots by.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Sort by creation time, falling back to modification time on filesystems that don't
+    /// report creation time.
+    Created,
+    /// Sort by modification time.
+    Modified,
+}
+
+impl FromStr for SortBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "created" => Ok(SortBy::Created),
+            "modified" => Ok(SortBy::Modified),
+            _ => bail!("expected `created` or `modified`, got {s:?}"),
+        }
+    }
+}
+
+/// Does `name` match `pattern`, where `pattern` may contain `*` wildcards matching any run of
+/// characters (including none)?
+pub fn matches_pattern(name: &str, pattern: &str) -> bool {
+    let name = name.as_bytes();
+    let pattern = pattern.as_bytes();
+
+    let (mut ni, mut pi) = (0, 0);
+    let (mut backtrack_ni, mut backtrack_pi) = (0, None);
+
+    while ni < name.len() {
+        if pi < pattern.len() && pattern[pi] == b'*' {
+            backtrack_pi = Some(pi);
+            backtrack_ni = ni;
+            pi += 1;
+        } else if pi < pattern.len() && pattern[pi] == name[ni] {
+            pi += 1;
+            ni += 1;
+        } else if let Some(star) = backtrack_pi {
+            pi = star + 1;
+            backtrack_ni += 1;
+            ni = backtrack_ni;
+        } else {
+            return false;
+        }
+    }
+
+    while pi < pattern.len() && pattern[pi] == b'*' {
+        pi += 1;
+    }
+
+    pi == pattern.len()
+}
+
+/// A file in the snapshot directory that matched the selection pattern, along with the timestamp
+/// it's being sorted by.
+struct Candidate {
+    path: PathBuf,
+    timestamp: SystemTime,
+}
+
+impl Candidate {
+    /// Inspect a `DirEntry`, returning `None` if it isn't a snapshot file we should consider.
+    fn from_dir_entry(dir_entry: DirEntry, pattern: &str, sort_by: SortBy) -> Result<Option<Self>> {
+        let path = dir_entry.path();
+
+        let metadata = dir_entry
+            .metadata()
+            .with_context(|| format!("couldn't get metadata of file {path:?}"))?;
+
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+
+        let Some(name) = path.file_name() else {
+            return Ok(None);
+        };
+        let name = name.to_string_lossy();
+
+        if !matches_pattern(&name, pattern) || name.contains("-vlc-print-out") {
+            return Ok(None);
+        }
+
+        let timestamp = match sort_by {
+            SortBy::Modified => metadata
+                .modified()
+                .with_context(|| format!("couldn't get modification time of file {path:?}"))?,
+            SortBy::Created => metadata.created().or_else(|_| metadata.modified()).with_context(
+                || format!("couldn't get a creation or modification time for file {path:?}"),
+            )?,
+        };
+
+        Ok(Some(Self { path, timestamp }))
+    }
+}
+
+/// Find the most recent file in `dir` whose name matches `pattern`, sorted by `sort_by`.
+pub fn most_recent_file(dir: &Path, pattern: &str, sort_by: SortBy) -> Result<PathBuf> {
+    let entries = fs::read_dir(dir).context("failed to read directory")?;
+
+    let mut candidates = Vec::new();
+    for entry in entries {
+        let candidate = entry
+            .context("couldn't read file in given directory")
+            .and_then(|entry| Candidate::from_dir_entry(entry, pattern, sort_by));
+
+        match candidate {
+            Ok(Some(candidate)) => candidates.push(candidate),
+            Ok(None) => (),
+            Err(e) => {
+                let mut chain = e.chain();
+                eprintln!("warning: {}\n", chain.next().unwrap());
+                for err in chain {
+                    eprintln!("caused by: {err}\n");
+                }
+            }
+        }
+    }
+
+    let latest = candidates.into_iter().max_by_key(|c| c.timestamp);
+
+    Ok(latest
+        .context("no files matching the given pattern in directory")?
+        .path)
+}